@@ -0,0 +1,213 @@
+use midi::{MidiMessage,MidiError,Status};
+use message::{Message,Note,U7,U14};
+use parser::Parser;
+
+/// A callback-dispatch façade over `Parser`.  Register a closure per
+/// message type you care about with `on_note_on`, `on_control_change`,
+/// etc, then feed it raw bytes with `handle_byte`/`handle_bytes`; the
+/// matching callback is invoked for each message the underlying
+/// parser completes.  This saves applications from writing their own
+/// `match` over every `Status` just to react to a live stream.
+pub struct Handler {
+    parser: Parser,
+    on_note_on: Option<Box<FnMut(u8, Note, U7)>>,
+    on_note_off: Option<Box<FnMut(u8, Note, U7)>>,
+    on_polyphonic_aftertouch: Option<Box<FnMut(u8, Note, U7)>>,
+    on_control_change: Option<Box<FnMut(u8, u8, U7)>>,
+    on_program_change: Option<Box<FnMut(u8, U7)>>,
+    on_channel_aftertouch: Option<Box<FnMut(u8, U7)>>,
+    on_pitch_bend: Option<Box<FnMut(u8, U14)>>,
+    on_sysex: Option<Box<FnMut(&MidiMessage)>>,
+    on_clock: Option<Box<FnMut()>>,
+    on_start: Option<Box<FnMut()>>,
+    on_continue: Option<Box<FnMut()>>,
+    on_stop: Option<Box<FnMut()>>,
+}
+
+impl Handler {
+    /// Create a new `Handler` with no callbacks registered
+    pub fn new() -> Handler {
+        Handler {
+            parser: Parser::new(),
+            on_note_on: None,
+            on_note_off: None,
+            on_polyphonic_aftertouch: None,
+            on_control_change: None,
+            on_program_change: None,
+            on_channel_aftertouch: None,
+            on_pitch_bend: None,
+            on_sysex: None,
+            on_clock: None,
+            on_start: None,
+            on_continue: None,
+            on_stop: None,
+        }
+    }
+
+    /// Register a callback for Note On messages
+    pub fn on_note_on<F>(&mut self, f: F) where F: FnMut(u8, Note, U7) + 'static {
+        self.on_note_on = Some(Box::new(f));
+    }
+
+    /// Register a callback for Note Off messages
+    pub fn on_note_off<F>(&mut self, f: F) where F: FnMut(u8, Note, U7) + 'static {
+        self.on_note_off = Some(Box::new(f));
+    }
+
+    /// Register a callback for Polyphonic Aftertouch messages
+    pub fn on_polyphonic_aftertouch<F>(&mut self, f: F) where F: FnMut(u8, Note, U7) + 'static {
+        self.on_polyphonic_aftertouch = Some(Box::new(f));
+    }
+
+    /// Register a callback for Control Change messages
+    pub fn on_control_change<F>(&mut self, f: F) where F: FnMut(u8, u8, U7) + 'static {
+        self.on_control_change = Some(Box::new(f));
+    }
+
+    /// Register a callback for Program Change messages
+    pub fn on_program_change<F>(&mut self, f: F) where F: FnMut(u8, U7) + 'static {
+        self.on_program_change = Some(Box::new(f));
+    }
+
+    /// Register a callback for Channel Aftertouch messages
+    pub fn on_channel_aftertouch<F>(&mut self, f: F) where F: FnMut(u8, U7) + 'static {
+        self.on_channel_aftertouch = Some(Box::new(f));
+    }
+
+    /// Register a callback for Pitch Bend messages
+    pub fn on_pitch_bend<F>(&mut self, f: F) where F: FnMut(u8, U14) + 'static {
+        self.on_pitch_bend = Some(Box::new(f));
+    }
+
+    /// Register a callback for System Exclusive messages, given the
+    /// raw `MidiMessage` (see `manufacturer_id()`/`payload()`)
+    pub fn on_sysex<F>(&mut self, f: F) where F: FnMut(&MidiMessage) + 'static {
+        self.on_sysex = Some(Box::new(f));
+    }
+
+    /// Register a callback for Timing Clock messages
+    pub fn on_clock<F>(&mut self, f: F) where F: FnMut() + 'static {
+        self.on_clock = Some(Box::new(f));
+    }
+
+    /// Register a callback for Start messages
+    pub fn on_start<F>(&mut self, f: F) where F: FnMut() + 'static {
+        self.on_start = Some(Box::new(f));
+    }
+
+    /// Register a callback for Continue messages
+    pub fn on_continue<F>(&mut self, f: F) where F: FnMut() + 'static {
+        self.on_continue = Some(Box::new(f));
+    }
+
+    /// Register a callback for Stop messages
+    pub fn on_stop<F>(&mut self, f: F) where F: FnMut() + 'static {
+        self.on_stop = Some(Box::new(f));
+    }
+
+    /// Feed a single byte into the underlying parser, invoking the
+    /// matching callback (if any is registered) for any message it
+    /// completes
+    pub fn handle_byte(&mut self, byte: u8) -> Result<(), MidiError> {
+        if let Some(msg) = try!(self.parser.parse_byte(byte)) {
+            self.dispatch(&msg);
+        }
+        Ok(())
+    }
+
+    /// Feed a slice of bytes into the underlying parser
+    pub fn handle_bytes(&mut self, bytes: &[u8]) -> Result<(), MidiError> {
+        for &byte in bytes.iter() {
+            try!(self.handle_byte(byte));
+        }
+        Ok(())
+    }
+
+    fn dispatch(&mut self, msg: &MidiMessage) {
+        match msg.status() {
+            Status::SysExStart => {
+                if let Some(ref mut f) = self.on_sysex { f(msg); }
+            }
+            Status::TimingClock => { if let Some(ref mut f) = self.on_clock { f(); } }
+            Status::Start => { if let Some(ref mut f) = self.on_start { f(); } }
+            Status::Continue => { if let Some(ref mut f) = self.on_continue { f(); } }
+            Status::Stop => { if let Some(ref mut f) = self.on_stop { f(); } }
+            _ => {
+                if let Ok(typed) = MidiMessage::parse(msg.as_bytes()) {
+                    match typed {
+                        Message::NoteOn { channel, note, velocity } => {
+                            if let Some(ref mut f) = self.on_note_on { f(channel, note, velocity); }
+                        }
+                        Message::NoteOff { channel, note, velocity } => {
+                            if let Some(ref mut f) = self.on_note_off { f(channel, note, velocity); }
+                        }
+                        Message::PolyphonicAftertouch { channel, note, pressure } => {
+                            if let Some(ref mut f) = self.on_polyphonic_aftertouch { f(channel, note, pressure); }
+                        }
+                        Message::ControlChange { channel, controller, value } => {
+                            if let Some(ref mut f) = self.on_control_change { f(channel, controller, value); }
+                        }
+                        Message::ProgramChange { channel, program } => {
+                            if let Some(ref mut f) = self.on_program_change { f(channel, program); }
+                        }
+                        Message::ChannelAftertouch { channel, pressure } => {
+                            if let Some(ref mut f) = self.on_channel_aftertouch { f(channel, pressure); }
+                        }
+                        Message::PitchBend { channel, value } => {
+                            if let Some(ref mut f) = self.on_pitch_bend { f(channel, value); }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::Handler;
+
+    // System Real-Time bytes have no channel nibble; if `status()`
+    // masked them down to SysExStart, none of these callbacks would
+    // ever fire and everything would land in on_sysex instead
+    #[test]
+    fn dispatches_system_real_time_bytes_to_their_own_callbacks() {
+        let clock = Rc::new(RefCell::new(0u32));
+        let start = Rc::new(RefCell::new(false));
+        let cont = Rc::new(RefCell::new(false));
+        let stop = Rc::new(RefCell::new(false));
+        let sysex = Rc::new(RefCell::new(false));
+
+        let mut handler = Handler::new();
+        {
+            let clock = clock.clone();
+            handler.on_clock(move || { *clock.borrow_mut() += 1; });
+        }
+        {
+            let start = start.clone();
+            handler.on_start(move || { *start.borrow_mut() = true; });
+        }
+        {
+            let cont = cont.clone();
+            handler.on_continue(move || { *cont.borrow_mut() = true; });
+        }
+        {
+            let stop = stop.clone();
+            handler.on_stop(move || { *stop.borrow_mut() = true; });
+        }
+        {
+            let sysex = sysex.clone();
+            handler.on_sysex(move |_msg| { *sysex.borrow_mut() = true; });
+        }
+
+        handler.handle_bytes(&[0xF8, 0xFA, 0xFB, 0xFC, 0xF8]).ok().unwrap();
+
+        assert_eq!(*clock.borrow(), 2);
+        assert!(*start.borrow());
+        assert!(*cont.borrow());
+        assert!(*stop.borrow());
+        assert!(!*sysex.borrow());
+    }
+}
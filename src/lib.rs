@@ -0,0 +1,17 @@
+//! rimd is a set of utilities to deal with midi messages and standard
+//! midi files (SMF).  See http://www.midi.org/techspecs/ for a
+//! description of the relevant specifications.
+
+pub use midi::{MidiMessage, MidiError, Status};
+pub use message::{Message, Note, ControlFunction, U7, U14};
+pub use parser::Parser;
+pub use smf::{SMF, MThd, MTrk, TrackEvent, Event, MetaEvent, SMFFormat, Division, SMFError};
+pub use mtc::{MtcAccumulator, SmpteTime, SmpteRate};
+pub use handler::Handler;
+
+pub mod midi;
+pub mod message;
+pub mod parser;
+pub mod smf;
+pub mod mtc;
+pub mod handler;
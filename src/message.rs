@@ -0,0 +1,221 @@
+use std::fmt;
+use std::num::FromPrimitive;
+
+use midi::{MidiError,MidiMessage,Status};
+
+/// A single note number in the range 0-127, as sent by Note On, Note
+/// Off and Polyphonic Aftertouch messages.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Note(u8);
+
+static NOTE_NAMES: [&'static str; 12] =
+    ["C","C#","D","D#","E","F","F#","G","G#","A","A#","B"];
+
+impl Note {
+    /// Create a `Note` from a raw byte, checking that it falls in the
+    /// valid 0-127 range
+    pub fn new(note: u8) -> Result<Note, MidiError> {
+        if note > 127 {
+            Err(MidiError::OtherErr("Note value out of range (0-127)"))
+        } else {
+            Ok(Note(note))
+        }
+    }
+
+    /// The raw note number
+    pub fn value(&self) -> u8 { self.0 }
+
+    /// The name of this note's pitch class, e.g. "C#"
+    pub fn name(&self) -> &'static str {
+        NOTE_NAMES[(self.0 % 12) as usize]
+    }
+
+    /// The octave this note falls in, using the convention that middle
+    /// C (note 60) is C4
+    pub fn octave(&self) -> i8 {
+        (self.0 / 12) as i8 - 1
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.name(), self.octave())
+    }
+}
+
+/// Names the standard Control Change controller numbers.  Controllers
+/// not listed here are still valid Control Change messages; use the
+/// raw `controller` field in that case.  See
+/// http://www.midi.org/techspecs/midimessages.php#3 for the full table.
+#[derive(FromPrimitive, Copy, Clone)]
+pub enum ControlFunction {
+    BankSelect = 0,
+    Modulation = 1,
+    BreathController = 2,
+    FootController = 4,
+    PortamentoTime = 5,
+    DataEntryMsb = 6,
+    Volume = 7,
+    Balance = 8,
+    Pan = 10,
+    ExpressionController = 11,
+    Sustain = 64,
+    Portamento = 65,
+    Sostenuto = 66,
+    SoftPedal = 67,
+    LegatoFootswitch = 68,
+    Hold2 = 69,
+    AllSoundOff = 120,
+    ResetAllControllers = 121,
+    LocalControl = 122,
+    AllNotesOff = 123,
+    OmniModeOff = 124,
+    OmniModeOn = 125,
+    MonoModeOn = 126,
+    PolyModeOn = 127,
+}
+
+impl ControlFunction {
+    /// Look up the named controller function for a raw CC number, if
+    /// this number has one
+    pub fn from_controller(controller: u8) -> Option<ControlFunction> {
+        FromPrimitive::from_u8(controller)
+    }
+}
+
+/// A validated 7-bit midi data value (0-127)
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct U7(u8);
+
+impl U7 {
+    /// Create a `U7`, checking that `value` fits in 7 bits
+    pub fn new(value: u8) -> Result<U7, MidiError> {
+        if value > 0x7F {
+            Err(MidiError::OtherErr("Value does not fit in 7 bits"))
+        } else {
+            Ok(U7(value))
+        }
+    }
+
+    /// The raw value
+    pub fn value(&self) -> u8 { self.0 }
+}
+
+/// A validated 14-bit midi data value, as carried by Pitch Bend and
+/// Song Position Pointer messages.  These are transmitted as an
+/// lsb/msb byte pair; center pitch bend is 8192.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct U14(u16);
+
+impl U14 {
+    /// Create a `U14`, checking that `value` fits in 14 bits
+    pub fn new(value: u16) -> Result<U14, MidiError> {
+        if value > 0x3FFF {
+            Err(MidiError::OtherErr("Value does not fit in 14 bits"))
+        } else {
+            Ok(U14(value))
+        }
+    }
+
+    /// Reassemble a 14-bit value from its least- and most-significant
+    /// 7-bit halves
+    pub fn from_parts(lsb: u8, msb: u8) -> U14 {
+        U14((((msb & 0x7F) as u16) << 7) | ((lsb & 0x7F) as u16))
+    }
+
+    /// Split back into `(lsb, msb)` 7-bit halves
+    pub fn to_parts(&self) -> (u8, u8) {
+        ((self.0 & 0x7F) as u8, (self.0 >> 7) as u8)
+    }
+
+    /// The raw value
+    pub fn value(&self) -> u16 { self.0 }
+}
+
+/// A semantically typed, decoded midi channel voice message.  Unlike
+/// `MidiMessage`, which just stores the raw bytes, this can be matched
+/// directly on the kind of message without indexing into `data()`.
+pub enum Message {
+    NoteOff { channel: u8, note: Note, velocity: U7 },
+    NoteOn { channel: u8, note: Note, velocity: U7 },
+    PolyphonicAftertouch { channel: u8, note: Note, pressure: U7 },
+    ControlChange { channel: u8, controller: u8, value: U7 },
+    ProgramChange { channel: u8, program: U7 },
+    ChannelAftertouch { channel: u8, pressure: U7 },
+    PitchBend { channel: u8, value: U14 },
+}
+
+impl Message {
+    /// Re-encode this message as raw midi bytes.  `channel` is a public
+    /// field on every variant and isn't validated on construction the
+    /// way `Note`/`U7`/`U14` are, so this checks it falls in the valid
+    /// 1-16 range before building the status byte.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MidiError> {
+        let channel = match *self {
+            Message::NoteOff { channel, .. } |
+            Message::NoteOn { channel, .. } |
+            Message::PolyphonicAftertouch { channel, .. } |
+            Message::ControlChange { channel, .. } |
+            Message::ProgramChange { channel, .. } |
+            Message::ChannelAftertouch { channel, .. } |
+            Message::PitchBend { channel, .. } => channel,
+        };
+        if channel < 1 || channel > 16 {
+            return Err(MidiError::OtherErr("Channel out of range (1-16)"));
+        }
+        Ok(match *self {
+            Message::NoteOff { channel, note, velocity } =>
+                vec![MidiMessage::make_status(Status::NoteOff, channel-1), note.value(), velocity.value()],
+            Message::NoteOn { channel, note, velocity } =>
+                vec![MidiMessage::make_status(Status::NoteOn, channel-1), note.value(), velocity.value()],
+            Message::PolyphonicAftertouch { channel, note, pressure } =>
+                vec![MidiMessage::make_status(Status::PolyphonicAftertouch, channel-1), note.value(), pressure.value()],
+            Message::ControlChange { channel, controller, value } =>
+                vec![MidiMessage::make_status(Status::ControlChange, channel-1), controller, value.value()],
+            Message::ProgramChange { channel, program } =>
+                vec![MidiMessage::make_status(Status::ProgramChange, channel-1), program.value()],
+            Message::ChannelAftertouch { channel, pressure } =>
+                vec![MidiMessage::make_status(Status::ChannelAftertouch, channel-1), pressure.value()],
+            Message::PitchBend { channel, value } => {
+                let (lsb, msb) = value.to_parts();
+                vec![MidiMessage::make_status(Status::PitchBend, channel-1), lsb, msb]
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::range;
+    use midi::MidiMessage;
+    use super::{Message,Note,U7};
+
+    #[test]
+    fn note_on_channel_round_trips() {
+        for channel in range(1u8, 17) {
+            let status = 0x90 | (channel - 1);
+            let bytes = vec![status, 0x40, 0x7F];
+            let parsed = MidiMessage::parse(&bytes[]).ok().unwrap();
+            assert_eq!(parsed.to_bytes().ok().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn control_change_channel_round_trips() {
+        let bytes = vec![0xB3, 0x07, 0x64]; // channel 4
+        let parsed = MidiMessage::parse(&bytes[]).ok().unwrap();
+        assert_eq!(parsed.to_bytes().ok().unwrap(), bytes);
+    }
+
+    // channel is a public, unvalidated field on every variant; a
+    // directly-constructed Message can carry an out-of-range value that
+    // would underflow the channel-1 subtraction
+    #[test]
+    fn to_bytes_rejects_out_of_range_channel() {
+        let msg = Message::NoteOn { channel: 0, note: Note::new(60).ok().unwrap(), velocity: U7::new(100).ok().unwrap() };
+        assert!(msg.to_bytes().is_err());
+
+        let msg = Message::NoteOn { channel: 17, note: Note::new(60).ok().unwrap(), velocity: U7::new(100).ok().unwrap() };
+        assert!(msg.to_bytes().is_err());
+    }
+}
@@ -3,6 +3,8 @@ use std::io::{IoError,Reader};
 use std::num::FromPrimitive;
 use std::fmt;
 
+use message::{Message,Note,U7,U14};
+
 /// An error that can occur trying to parse a midi message
 pub enum MidiError {
     InvalidStatus(u8),
@@ -82,10 +84,17 @@ pub struct MidiMessage {
 static STATUS_MASK: u8 = 0xF0;
 static CHANNEL_MASK: u8 = 0x0F;
 
+// System Common and System Real-Time statuses (0xF0-0xFF) have no
+// channel nibble to mask off; only channel voice statuses (0x80-0xEF)
+// need the low nibble stripped before they can be looked up in `Status`
+fn status_kind(status: u8) -> u8 {
+    if status >= 0xF0 { status } else { status & STATUS_MASK }
+}
+
 impl MidiMessage {
     /// Return the status (type) of this message
     pub fn status(&self) -> Status {
-        FromPrimitive::from_u8(self.data[0] & STATUS_MASK).unwrap()
+        FromPrimitive::from_u8(status_kind(self.data[0])).unwrap()
     }
 
     /// Return the channel this message is on (TODO: return 0 for messages with no channel)
@@ -99,8 +108,13 @@ impl MidiMessage {
         self.data[index]
     }
 
+    /// This message's raw bytes, status byte included
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[]
+    }
+
     // Or in the channel bits to a status
-    fn make_status(status: Status, channel: u8) -> u8 {
+    pub fn make_status(status: Status, channel: u8) -> u8 {
         status as u8 | channel
     }
 
@@ -116,8 +130,8 @@ impl MidiMessage {
     // -1 -> variable sized message, call get_variable_size
     // -2 -> sysex, read until SysExEnd
     // -3 -> invalid status
-    fn data_bytes(status: u8) -> isize {
-        match FromPrimitive::from_u8(status & STATUS_MASK) {
+    pub fn data_bytes(status: u8) -> isize {
+        match FromPrimitive::from_u8(status_kind(status)) {
             Some(stat) => {
                 match stat {
                     Status::NoteOff |
@@ -159,18 +173,145 @@ impl MidiMessage {
             2 => { ret.push(try!(reader.read_byte()));
                    ret.push(try!(reader.read_byte())); }
             -1 => { return Err(MidiError::OtherErr("Don't handle variable sized yet")); }
-            -2 => { return Err(MidiError::OtherErr("Don't handle sysex yet")); }
+            -2 => {
+                loop {
+                    let b = try!(reader.read_byte());
+                    if b >= 0xF8 {
+                        // System Real-Time bytes may be interleaved inside
+                        // a sysex stream; they don't belong to its payload
+                        continue;
+                    }
+                    ret.push(b);
+                    if b == Status::SysExEnd as u8 {
+                        break;
+                    }
+                }
+            }
             _ =>  { return Err(MidiError::InvalidStatus(stat)); }
         }
         Ok(MidiMessage{data: ret})
     }
 
+    /// Create a System Exclusive message, framing `payload` with the
+    /// given `manufacturer_id` (one byte, or three for the extended
+    /// `0x00 nn nn` id form) between the `0xF0`/`0xF7` sysex bytes.
+    pub fn sysex(manufacturer_id: &[u8], payload: &[u8]) -> Result<MidiMessage, MidiError> {
+        if manufacturer_id.len() != 1 && manufacturer_id.len() != 3 {
+            return Err(MidiError::OtherErr("SysEx manufacturer id must be 1 or 3 bytes"));
+        }
+        let mut data: Vec<u8> = Vec::with_capacity(2 + manufacturer_id.len() + payload.len());
+        data.push(Status::SysExStart as u8);
+        data.push_all(manufacturer_id);
+        data.push_all(payload);
+        data.push(Status::SysExEnd as u8);
+        Ok(MidiMessage { data: data })
+    }
+
+    /// The manufacturer id bytes of this SysEx message: one byte, or
+    /// three for the extended `0x00 nn nn` form, or empty if this
+    /// message carries no manufacturer id at all (e.g. a bare `0xF0
+    /// 0xF7`).  Only meaningful when `status()` is `SysExStart`.
+    pub fn manufacturer_id(&self) -> &[u8] {
+        let available = if self.data.len() >= 2 { self.data.len() - 2 } else { 0 };
+        let len = if available >= 3 && self.data.len() > 1 && self.data[1] == 0 {
+            3
+        } else if available >= 1 {
+            1
+        } else {
+            0
+        };
+        &self.data[1 .. 1+len]
+    }
+
+    /// The SysEx payload, excluding the manufacturer id and the
+    /// `0xF0`/`0xF7` framing bytes.  Only meaningful when `status()`
+    /// is `SysExStart`.
+    pub fn payload(&self) -> &[u8] {
+        let id_len = self.manufacturer_id().len();
+        let start = 1 + id_len;
+        if self.data.len() <= start {
+            return &[];
+        }
+        &self.data[1+id_len .. self.data.len()-1]
+    }
+
+    /// This MIDI Time Code quarter-frame message's piece index (0-7)
+    /// and its 4-bit value.  Only meaningful when `status()` is
+    /// `MIDITimeCodeQtrFrame`; combine eight of these with
+    /// `MtcAccumulator` to get a full `SmpteTime`.
+    pub fn quarter_frame(&self) -> (u8, u8) {
+        let b = self.data[1];
+        ((b >> 4) & 0x07, b & 0x0F)
+    }
+
+    /// This message's 14-bit Song Position Pointer value, in MIDI
+    /// beats (sixteenth notes) since the start of the song.  Only
+    /// meaningful when `status()` is `SongPositionPointer`.
+    pub fn song_position(&self) -> U14 {
+        U14::from_parts(self.data[1], self.data[2])
+    }
+
     /// Extract next midi message from a reader
     pub fn next_message(reader: &mut Reader) -> Result<MidiMessage,MidiError> {
         let stat = try!(reader.read_byte());
         MidiMessage::next_message_given_status(stat,reader)
     }
 
+    /// Decode this message's raw bytes into a semantically typed
+    /// `Message`.  This only succeeds for channel voice messages
+    /// (note on/off, control change, etc) since those are the messages
+    /// that have a well defined, fixed-size typed representation.
+    pub fn parse(bytes: &[u8]) -> Result<Message, MidiError> {
+        if bytes.len() == 0 {
+            return Err(MidiError::OtherErr("No data to parse"));
+        }
+        let stat = bytes[0];
+        let channel = (stat & CHANNEL_MASK) + 1;
+        let status: Status = match FromPrimitive::from_u8(status_kind(stat)) {
+            Some(s) => s,
+            None => return Err(MidiError::InvalidStatus(stat)),
+        };
+        let need = MidiMessage::data_bytes(stat);
+        if need > 0 && bytes.len() < 1 + need as usize {
+            return Err(MidiError::OtherErr("Not enough data bytes for this status"));
+        }
+        match status {
+            Status::NoteOff => Ok(Message::NoteOff {
+                channel: channel,
+                note: try!(Note::new(bytes[1])),
+                velocity: try!(U7::new(bytes[2])),
+            }),
+            Status::NoteOn => Ok(Message::NoteOn {
+                channel: channel,
+                note: try!(Note::new(bytes[1])),
+                velocity: try!(U7::new(bytes[2])),
+            }),
+            Status::PolyphonicAftertouch => Ok(Message::PolyphonicAftertouch {
+                channel: channel,
+                note: try!(Note::new(bytes[1])),
+                pressure: try!(U7::new(bytes[2])),
+            }),
+            Status::ControlChange => Ok(Message::ControlChange {
+                channel: channel,
+                controller: bytes[1],
+                value: try!(U7::new(bytes[2])),
+            }),
+            Status::ProgramChange => Ok(Message::ProgramChange {
+                channel: channel,
+                program: try!(U7::new(bytes[1])),
+            }),
+            Status::ChannelAftertouch => Ok(Message::ChannelAftertouch {
+                channel: channel,
+                pressure: try!(U7::new(bytes[1])),
+            }),
+            Status::PitchBend => Ok(Message::PitchBend {
+                channel: channel,
+                value: U14::from_parts(bytes[1], bytes[2]),
+            }),
+            _ => Err(MidiError::OtherErr("Message type has no typed representation")),
+        }
+    }
+
 
     // Functions to build midi messages
 
@@ -276,3 +417,36 @@ impl fmt::Display for MidiMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MidiMessage;
+
+    #[test]
+    fn empty_sysex_payload_does_not_panic() {
+        let msg = MidiMessage::from_bytes(vec![0xF0, 0xF7]);
+        let empty: Vec<u8> = vec![];
+        assert_eq!(msg.manufacturer_id().to_vec(), empty);
+        assert_eq!(msg.payload().to_vec(), empty);
+    }
+
+    #[test]
+    fn sysex_rejects_bad_manufacturer_id_length() {
+        assert!(MidiMessage::sysex(&[], &[1, 2, 3]).is_err());
+        assert!(MidiMessage::sysex(&[0x41, 0x42], &[]).is_err());
+    }
+
+    #[test]
+    fn sysex_round_trips_one_byte_id() {
+        let msg = MidiMessage::sysex(&[0x41], &[1, 2, 3]).ok().unwrap();
+        assert_eq!(msg.manufacturer_id().to_vec(), vec![0x41]);
+        assert_eq!(msg.payload().to_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn sysex_round_trips_extended_id() {
+        let msg = MidiMessage::sysex(&[0x00, 0x01, 0x02], &[9]).ok().unwrap();
+        assert_eq!(msg.manufacturer_id().to_vec(), vec![0x00, 0x01, 0x02]);
+        assert_eq!(msg.payload().to_vec(), vec![9]);
+    }
+}
@@ -0,0 +1,103 @@
+use midi::MidiMessage;
+
+/// The frame rate carried in the top bits of an MTC full frame, as
+/// used by `SmpteTime`
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SmpteRate {
+    Fps24,
+    Fps25,
+    Fps29_97Drop,
+    Fps30,
+}
+
+/// A fully reassembled SMPTE timecode, as carried across eight MIDI
+/// Time Code quarter-frame messages
+#[derive(Copy, Clone)]
+pub struct SmpteTime {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub rate: SmpteRate,
+}
+
+/// Reassembles a full `SmpteTime` out of a stream of MIDI Time Code
+/// quarter-frame (`0xF1`) messages.  Each quarter-frame carries one of
+/// eight pieces (its nibble index, 0-7) and a 4-bit value in its
+/// single data byte; the full timecode is only known once all eight
+/// pieces have arrived, which takes two video frames on the wire.
+pub struct MtcAccumulator {
+    nibbles: [u8; 8],
+    have: u8,
+}
+
+impl MtcAccumulator {
+    /// Create a new, empty accumulator
+    pub fn new() -> MtcAccumulator {
+        MtcAccumulator { nibbles: [0; 8], have: 0 }
+    }
+
+    /// Feed a single MIDI Time Code quarter-frame message.  Returns
+    /// the reassembled `SmpteTime` once all eight pieces have been
+    /// seen since the last complete time.
+    pub fn feed(&mut self, msg: &MidiMessage) -> Option<SmpteTime> {
+        let (piece, value) = msg.quarter_frame();
+        self.nibbles[piece as usize] = value;
+        self.have |= 1 << (piece as u32);
+        if self.have == 0xFF {
+            self.have = 0;
+            Some(assemble(&self.nibbles))
+        } else {
+            None
+        }
+    }
+}
+
+fn assemble(n: &[u8; 8]) -> SmpteTime {
+    let frames = n[0] | (n[1] << 4);
+    let seconds = n[2] | (n[3] << 4);
+    let minutes = n[4] | (n[5] << 4);
+    let hours_and_rate = n[6] | (n[7] << 4);
+    let hours = hours_and_rate & 0x1F;
+    let rate = match (hours_and_rate >> 5) & 0x03 {
+        0 => SmpteRate::Fps24,
+        1 => SmpteRate::Fps25,
+        2 => SmpteRate::Fps29_97Drop,
+        _ => SmpteRate::Fps30,
+    };
+    SmpteTime { hours: hours, minutes: minutes, seconds: seconds, frames: frames, rate: rate }
+}
+
+#[cfg(test)]
+mod tests {
+    use parser::Parser;
+    use super::{MtcAccumulator,SmpteRate};
+
+    // frames=24, seconds=30, minutes=45, hours=13, rate=Fps25
+    #[test]
+    fn reassembles_full_smpte_time_from_quarter_frames_via_parser() {
+        let pieces = [8u8, 1, 14, 1, 13, 2, 13, 2];
+        let mut bytes: Vec<u8> = Vec::new();
+        for (i, &value) in pieces.iter().enumerate() {
+            bytes.push(0xF1);
+            bytes.push(((i as u8) << 4) | value);
+        }
+
+        let mut parser = Parser::new();
+        let messages = parser.parse_bytes(&bytes[]).ok().unwrap();
+        assert_eq!(messages.len(), 8);
+
+        let mut acc = MtcAccumulator::new();
+        let mut result = None;
+        for msg in messages.iter() {
+            result = acc.feed(msg);
+        }
+
+        let time = result.unwrap();
+        assert_eq!(time.frames, 24);
+        assert_eq!(time.seconds, 30);
+        assert_eq!(time.minutes, 45);
+        assert_eq!(time.hours, 13);
+        assert!(time.rate == SmpteRate::Fps25);
+    }
+}
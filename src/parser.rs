@@ -0,0 +1,194 @@
+use midi::{MidiMessage,MidiError,Status};
+
+/// An incremental midi message parser, suitable for driving from a
+/// live input port where bytes arrive one at a time.  Unlike
+/// `MidiMessage::next_message`, which reads a whole message from a
+/// `Reader` in one shot, `Parser` is fed one byte (or a slice of
+/// bytes) at a time and hands back completed messages as they become
+/// available.
+///
+/// This implements "running status": once a channel voice status byte
+/// has been seen, subsequent data bytes with no new status byte are
+/// assumed to belong to another message of that same status.  System
+/// Real-Time messages (0xF8-0xFF) are single bytes that may appear in
+/// the middle of another message's data stream (e.g. a clock tick
+/// between the two data bytes of a Note On, or inside a sysex
+/// payload); they are emitted immediately without touching the
+/// running status or the in-progress buffer.  Any other System Common
+/// message (0xF0-0xF7) cancels running status, per the spec.
+pub struct Parser {
+    running_status: Option<u8>,
+    buffer: Vec<u8>,
+    needed: isize,
+}
+
+impl Parser {
+    /// Create a new, empty parser
+    pub fn new() -> Parser {
+        Parser {
+            running_status: None,
+            buffer: Vec::with_capacity(3),
+            needed: 0,
+        }
+    }
+
+    // Start buffering a message for the given status byte, completing
+    // it immediately if it takes no data bytes
+    fn begin(&mut self, status: u8) -> Result<Option<MidiMessage>, MidiError> {
+        self.buffer.clear();
+        self.buffer.push(status);
+        self.needed = MidiMessage::data_bytes(status);
+        match self.needed {
+            -3 => { self.buffer.clear(); Err(MidiError::InvalidStatus(status)) }
+            -2 => Ok(None), // sysex: keep collecting until SysExEnd
+            -1 => { self.buffer.clear(); Err(MidiError::OtherErr("Don't handle variable sized yet")) }
+            0  => { let msg = MidiMessage::from_bytes(self.buffer.clone()); self.buffer.clear(); Ok(Some(msg)) }
+            _  => Ok(None),
+        }
+    }
+
+    // A new status byte has arrived, whether at the top level or
+    // interrupting an in-progress sysex.  Update running status exactly
+    // the same way in both cases: System Common cancels it, a channel
+    // voice status becomes the new running status.
+    fn status_byte(&mut self, byte: u8) -> Result<Option<MidiMessage>, MidiError> {
+        if byte >= 0xF0 {
+            // System Common cancels running status
+            self.running_status = None;
+        } else {
+            self.running_status = Some(byte);
+        }
+        self.begin(byte)
+    }
+
+    /// Feed a single byte into the parser.  Returns `Some(message)`
+    /// whenever this byte completes a message, `None` while more
+    /// bytes are still needed to complete the current message.
+    pub fn parse_byte(&mut self, byte: u8) -> Result<Option<MidiMessage>, MidiError> {
+        // System Real-Time: always one byte, may be interleaved inside
+        // another message (including a sysex payload) without
+        // disturbing it.  Reserved/undefined real-time bytes (e.g.
+        // 0xF9, 0xFD) have no defined Status and must be rejected here,
+        // rather than handed back as a message whose status() panics.
+        if byte >= 0xF8 {
+            if MidiMessage::data_bytes(byte) == -3 {
+                return Err(MidiError::InvalidStatus(byte));
+            }
+            return Ok(Some(MidiMessage::from_bytes(vec![byte])));
+        }
+
+        if self.needed == -2 {
+            // Mid-sysex: collect data bytes until the terminator
+            if byte == Status::SysExEnd as u8 {
+                self.buffer.push(byte);
+                let msg = MidiMessage::from_bytes(self.buffer.clone());
+                self.buffer.clear();
+                self.needed = 0;
+                return Ok(Some(msg));
+            }
+            if byte & 0x80 != 0 {
+                // any other status byte abandons the sysex, per spec
+                return self.status_byte(byte);
+            }
+            self.buffer.push(byte);
+            return Ok(None);
+        }
+
+        if byte & 0x80 != 0 {
+            return self.status_byte(byte);
+        }
+
+        // A data byte with nothing buffered means we're relying on
+        // running status from a previous message
+        if self.buffer.len() == 0 {
+            match self.running_status {
+                Some(status) => try!(self.begin(status)),
+                None => return Err(MidiError::OtherErr("Data byte received with no status")),
+            };
+        }
+
+        self.buffer.push(byte);
+        if self.needed >= 0 && (self.buffer.len() - 1) as isize >= self.needed {
+            let msg = MidiMessage::from_bytes(self.buffer.clone());
+            self.buffer.clear();
+            Ok(Some(msg))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Feed a slice of bytes into the parser, returning every message
+    /// completed along the way, in order
+    pub fn parse_bytes(&mut self, bytes: &[u8]) -> Result<Vec<MidiMessage>, MidiError> {
+        let mut out = Vec::new();
+        for &byte in bytes.iter() {
+            match try!(self.parse_byte(byte)) {
+                Some(msg) => out.push(msg),
+                None => {}
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi::Status;
+    use super::Parser;
+
+    // 0xF1-0xF6 have no channel nibble; masking them down to 0xF0
+    // would misclassify them as the start of a SysEx stream and eat
+    // every following byte as bogus payload
+    #[test]
+    fn mtc_quarter_frame_is_not_mistaken_for_sysex() {
+        let mut parser = Parser::new();
+        let messages = parser.parse_bytes(&[0xF1, 0x23]).ok().unwrap();
+        assert_eq!(messages.len(), 1);
+        match messages[0].status() {
+            Status::MIDITimeCodeQtrFrame => {}
+            _ => panic!("expected MIDITimeCodeQtrFrame"),
+        }
+    }
+
+    #[test]
+    fn song_position_is_not_mistaken_for_sysex() {
+        let mut parser = Parser::new();
+        let messages = parser.parse_bytes(&[0xF2, 0x10, 0x20]).ok().unwrap();
+        assert_eq!(messages.len(), 1);
+        match messages[0].status() {
+            Status::SongPositionPointer => {}
+            _ => panic!("expected SongPositionPointer"),
+        }
+    }
+
+    // 0xF9 and 0xFD are reserved and have no defined Status; handing
+    // one back as a message would make status() panic downstream
+    #[test]
+    fn reserved_real_time_bytes_are_rejected() {
+        let mut parser = Parser::new();
+        assert!(parser.parse_byte(0xF9).is_err());
+        assert!(parser.parse_byte(0xFD).is_err());
+    }
+
+    // A channel voice status interrupting an in-progress sysex should
+    // become the new running status, exactly as it would if it had
+    // arrived outside a sysex
+    #[test]
+    fn status_interrupting_sysex_becomes_running_status() {
+        let mut parser = Parser::new();
+        let messages = parser.parse_bytes(&[
+            0xF0, 0x7D, 0x01, // sysex start, abandoned mid-payload
+            0x92, 0x40, 0x7F, // Note On, channel 3 (explicit status)
+            0x41, 0x7E,       // Note On via running status, no status byte
+        ]).ok().unwrap();
+        assert_eq!(messages.len(), 2);
+        match messages[0].status() {
+            Status::NoteOn => {}
+            _ => panic!("expected NoteOn"),
+        }
+        match messages[1].status() {
+            Status::NoteOn => {}
+            _ => panic!("expected NoteOn via running status"),
+        }
+    }
+}
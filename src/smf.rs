@@ -0,0 +1,444 @@
+use std::error;
+use std::fmt;
+use std::io::{IoError,IoResult,Reader,Writer};
+use std::iter::range;
+
+use midi::{MidiMessage,MidiError};
+
+/// An error that can occur reading or writing a Standard Midi File
+pub enum SMFError {
+    InvalidSMFFile(&'static str),
+    MidiError(MidiError),
+    IoError(IoError),
+}
+
+impl error::FromError<IoError> for SMFError {
+    fn from_error(err: IoError) -> SMFError {
+        SMFError::IoError(err)
+    }
+}
+
+impl error::FromError<MidiError> for SMFError {
+    fn from_error(err: MidiError) -> SMFError {
+        SMFError::MidiError(err)
+    }
+}
+
+impl error::Error for SMFError {
+    fn description(&self) -> &str {
+        match *self {
+            SMFError::InvalidSMFFile(_) => "The SMF file is malformed",
+            SMFError::MidiError(ref e) => e.description(),
+            SMFError::IoError(ref e) => e.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            SMFError::MidiError(ref err) => Some(err as &error::Error),
+            SMFError::IoError(ref err) => Some(err as &error::Error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SMFError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SMFError::InvalidSMFFile(ref s) => write!(f,"Invalid SMF file: {}",s),
+            SMFError::MidiError(ref e) => write!(f,"{}",e),
+            SMFError::IoError(ref e) => write!(f,"{}",e),
+        }
+    }
+}
+
+/// The format of a Standard Midi File, from its `MThd` chunk
+#[derive(Copy, Clone)]
+pub enum SMFFormat {
+    /// A single multi-channel track
+    Single = 0,
+    /// One or more tracks, played simultaneously
+    MultiTrack = 1,
+    /// One or more sequentially independent tracks
+    MultiSong = 2,
+}
+
+/// How the `delta` field of a `TrackEvent` should be interpreted
+#[derive(Copy, Clone)]
+pub enum Division {
+    /// `delta` is measured in ticks per quarter note
+    TicksPerQuarterNote(u16),
+    /// `delta` is measured in ticks per SMPTE frame
+    SMPTE { fps: u8, ticks_per_frame: u8 },
+}
+
+/// The header chunk of a Standard Midi File
+pub struct MThd {
+    pub format: SMFFormat,
+    pub num_tracks: u16,
+    pub division: Division,
+}
+
+impl MThd {
+    /// Read an `MThd` chunk from `reader`
+    pub fn read(reader: &mut Reader) -> Result<MThd, SMFError> {
+        let tag = try!(read_chunk_tag(reader));
+        if &tag[] != &b"MThd"[] {
+            return Err(SMFError::InvalidSMFFile("Missing MThd chunk"));
+        }
+        let len = try!(reader.read_be_u32());
+        if len != 6 {
+            return Err(SMFError::InvalidSMFFile("Unexpected MThd chunk length"));
+        }
+        let format = match try!(reader.read_be_u16()) {
+            0 => SMFFormat::Single,
+            1 => SMFFormat::MultiTrack,
+            2 => SMFFormat::MultiSong,
+            _ => return Err(SMFError::InvalidSMFFile("Unknown SMF format")),
+        };
+        let num_tracks = try!(reader.read_be_u16());
+        let division_raw = try!(reader.read_be_u16());
+        let division = if division_raw & 0x8000 == 0 {
+            Division::TicksPerQuarterNote(division_raw)
+        } else {
+            let top = (division_raw >> 8) as u32;
+            Division::SMPTE {
+                fps: (256 - top) as u8,
+                ticks_per_frame: (division_raw & 0xFF) as u8,
+            }
+        };
+        Ok(MThd { format: format, num_tracks: num_tracks, division: division })
+    }
+
+    /// Write this `MThd` chunk to `writer`
+    pub fn write(&self, writer: &mut Writer) -> IoResult<()> {
+        try!(writer.write(b"MThd"));
+        try!(writer.write_be_u32(6));
+        try!(writer.write_be_u16(self.format as u16));
+        try!(writer.write_be_u16(self.num_tracks));
+        let division_raw = match self.division {
+            Division::TicksPerQuarterNote(ticks) => ticks,
+            Division::SMPTE { fps, ticks_per_frame } => {
+                let top = (256 - fps as u32) as u16;
+                (top << 8) | (ticks_per_frame as u16)
+            }
+        };
+        writer.write_be_u16(division_raw)
+    }
+}
+
+/// A decoded meta event, as found in a track's `0xFF` events
+pub enum MetaEvent {
+    /// Microseconds per quarter note
+    Tempo(u32),
+    TimeSignature { numerator: u8, denominator: u8, clocks_per_click: u8, notated_32nd_per_quarter: u8 },
+    /// Sharps (positive) or flats (negative), and whether the key is minor
+    KeySignature { sharps_flats: i8, is_minor: bool },
+    TrackName(String),
+    EndOfTrack,
+    /// Any meta event type this module doesn't decode further
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+/// A single event in a track, either a channel voice message, a sysex
+/// message (the bytes following its Standard Midi File length prefix;
+/// the leading `0xF0`/`0xF7` byte is not kept), or a meta event
+pub enum Event {
+    Midi(MidiMessage),
+    SysEx(Vec<u8>),
+    Meta(MetaEvent),
+}
+
+/// An event together with the number of ticks (per `MThd::division`)
+/// since the previous event in its track
+pub struct TrackEvent {
+    pub delta: u32,
+    pub event: Event,
+}
+
+/// An `MTrk` chunk: the ordered events that make up one track
+pub struct MTrk {
+    pub events: Vec<TrackEvent>,
+}
+
+impl MTrk {
+    /// Read an `MTrk` chunk from `reader`
+    pub fn read(reader: &mut Reader) -> Result<MTrk, SMFError> {
+        let tag = try!(read_chunk_tag(reader));
+        if &tag[] != &b"MTrk"[] {
+            return Err(SMFError::InvalidSMFFile("Missing MTrk chunk"));
+        }
+        let len = try!(reader.read_be_u32());
+        let mut counting = CountingReader::new(reader);
+        let mut events = Vec::new();
+        let mut running_status: Option<u8> = None;
+        while counting.count() < len as u64 {
+            let delta = try!(read_vlq(&mut counting));
+            let stat = try!(counting.read_byte());
+            if stat & 0x80 == 0 {
+                // running status: this byte is actually the first data byte
+                let status = match running_status {
+                    Some(s) => s,
+                    None => return Err(SMFError::InvalidSMFFile("Running status with no prior status")),
+                };
+                let event = try!(read_channel_event(&mut counting, status, Some(stat)));
+                events.push(TrackEvent { delta: delta, event: Event::Midi(event) });
+            } else if stat == 0xFF {
+                running_status = None;
+                let kind = try!(counting.read_byte());
+                let meta_len = try!(read_vlq(&mut counting));
+                if meta_len as u64 > (len as u64).saturating_sub(counting.count()) {
+                    return Err(SMFError::InvalidSMFFile("Meta event length runs past end of track"));
+                }
+                let mut data = Vec::with_capacity(meta_len as usize);
+                for _ in range(0u32, meta_len) {
+                    data.push(try!(counting.read_byte()));
+                }
+                events.push(TrackEvent { delta: delta, event: Event::Meta(decode_meta(kind, data)) });
+            } else if stat == 0xF0 || stat == 0xF7 {
+                running_status = None;
+                let sysex_len = try!(read_vlq(&mut counting));
+                if sysex_len as u64 > (len as u64).saturating_sub(counting.count()) {
+                    return Err(SMFError::InvalidSMFFile("SysEx event length runs past end of track"));
+                }
+                let mut data = Vec::with_capacity(sysex_len as usize);
+                for _ in range(0u32, sysex_len) {
+                    data.push(try!(counting.read_byte()));
+                }
+                events.push(TrackEvent { delta: delta, event: Event::SysEx(data) });
+            } else {
+                running_status = Some(stat);
+                let event = try!(read_channel_event(&mut counting, stat, None));
+                events.push(TrackEvent { delta: delta, event: Event::Midi(event) });
+            }
+        }
+        Ok(MTrk { events: events })
+    }
+
+    /// Write this `MTrk` chunk to `writer`
+    pub fn write(&self, writer: &mut Writer) -> Result<(), SMFError> {
+        let mut body: Vec<u8> = Vec::new();
+        for ev in self.events.iter() {
+            try!(write_vlq(&mut body, ev.delta));
+            match ev.event {
+                Event::Midi(ref msg) => {
+                    body.push_all(msg.as_bytes());
+                }
+                Event::SysEx(ref data) => {
+                    body.push(0xF0);
+                    try!(write_vlq(&mut body, data.len() as u32));
+                    body.push_all(&data[]);
+                }
+                Event::Meta(ref meta) => {
+                    body.push(0xFF);
+                    let (kind, data) = encode_meta(meta);
+                    body.push(kind);
+                    try!(write_vlq(&mut body, data.len() as u32));
+                    body.push_all(&data[]);
+                }
+            }
+        }
+        try!(writer.write(b"MTrk"));
+        try!(writer.write_be_u32(body.len() as u32));
+        try!(writer.write(&body[]));
+        Ok(())
+    }
+}
+
+/// A full Standard Midi File: its header and all its tracks
+pub struct SMF {
+    pub header: MThd,
+    pub tracks: Vec<MTrk>,
+}
+
+impl SMF {
+    /// Read a whole `.mid` file from `reader`
+    pub fn read(reader: &mut Reader) -> Result<SMF, SMFError> {
+        let header = try!(MThd::read(reader));
+        let mut tracks = Vec::with_capacity(header.num_tracks as usize);
+        for _ in range(0, header.num_tracks) {
+            tracks.push(try!(MTrk::read(reader)));
+        }
+        Ok(SMF { header: header, tracks: tracks })
+    }
+
+    /// Write this `.mid` file to `writer`
+    pub fn write(&self, writer: &mut Writer) -> Result<(), SMFError> {
+        try!(self.header.write(writer));
+        for track in self.tracks.iter() {
+            try!(track.write(writer));
+        }
+        Ok(())
+    }
+}
+
+// Wraps a `Reader`, counting the bytes read through it so `MTrk::read`
+// knows when it has consumed the chunk's declared length
+struct CountingReader<'a> {
+    inner: &'a mut Reader,
+    count: u64,
+}
+
+impl<'a> CountingReader<'a> {
+    fn new(inner: &'a mut Reader) -> CountingReader<'a> {
+        CountingReader { inner: inner, count: 0 }
+    }
+
+    fn count(&self) -> u64 { self.count }
+}
+
+impl<'a> Reader for CountingReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let n = try!(self.inner.read(buf));
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+fn read_chunk_tag(reader: &mut Reader) -> Result<[u8; 4], SMFError> {
+    let mut tag = [0u8; 4];
+    for i in range(0u, 4) {
+        tag[i] = try!(reader.read_byte());
+    }
+    Ok(tag)
+}
+
+// Read a channel voice event's data bytes, given its status byte and
+// (when running status supplied the first data byte already) that
+// byte, producing the complete raw `MidiMessage`
+fn read_channel_event(reader: &mut Reader, status: u8, first_data_byte: Option<u8>) -> Result<MidiMessage, MidiError> {
+    let need = MidiMessage::data_bytes(status);
+    if need < 0 {
+        // negative means sysex or an undefined status, neither of which belong here
+        return Err(MidiError::InvalidStatus(status));
+    }
+    let mut data: Vec<u8> = Vec::with_capacity(3);
+    data.push(status);
+    match first_data_byte {
+        Some(b) => data.push(b),
+        None => {}
+    }
+    while (data.len() - 1) < need as usize {
+        data.push(try!(reader.read_byte()));
+    }
+    Ok(MidiMessage::from_bytes(data))
+}
+
+fn decode_meta(kind: u8, data: Vec<u8>) -> MetaEvent {
+    match kind {
+        0x51 if data.len() == 3 =>
+            MetaEvent::Tempo(((data[0] as u32) << 16) | ((data[1] as u32) << 8) | (data[2] as u32)),
+        0x58 if data.len() == 4 =>
+            MetaEvent::TimeSignature {
+                numerator: data[0],
+                denominator: data[1],
+                clocks_per_click: data[2],
+                notated_32nd_per_quarter: data[3],
+            },
+        0x59 if data.len() == 2 =>
+            MetaEvent::KeySignature { sharps_flats: data[0] as i8, is_minor: data[1] != 0 },
+        0x03 => MetaEvent::TrackName(String::from_utf8_lossy(&data[]).into_owned()),
+        0x2F => MetaEvent::EndOfTrack,
+        _ => MetaEvent::Unknown { kind: kind, data: data },
+    }
+}
+
+fn encode_meta(meta: &MetaEvent) -> (u8, Vec<u8>) {
+    match *meta {
+        MetaEvent::Tempo(usec) =>
+            (0x51, vec![((usec >> 16) & 0xFF) as u8, ((usec >> 8) & 0xFF) as u8, (usec & 0xFF) as u8]),
+        MetaEvent::TimeSignature { numerator, denominator, clocks_per_click, notated_32nd_per_quarter } =>
+            (0x58, vec![numerator, denominator, clocks_per_click, notated_32nd_per_quarter]),
+        MetaEvent::KeySignature { sharps_flats, is_minor } =>
+            (0x59, vec![sharps_flats as u8, if is_minor { 1 } else { 0 }]),
+        MetaEvent::TrackName(ref name) => (0x03, name.clone().into_bytes()),
+        MetaEvent::EndOfTrack => (0x2F, vec![]),
+        MetaEvent::Unknown { kind, ref data } => (kind, data.clone()),
+    }
+}
+
+// Decode a variable length quantity: 7 bits per byte, most significant
+// group first, continuation indicated by the high bit
+fn read_vlq(reader: &mut Reader) -> Result<u32, SMFError> {
+    let mut value: u32 = 0;
+    for _ in range(0u, 4) {
+        let b = try!(reader.read_byte());
+        value = (value << 7) | ((b & 0x7F) as u32);
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(SMFError::InvalidSMFFile("Variable length quantity longer than 4 bytes"))
+}
+
+// Largest value that fits in the 4-byte/28-bit variable length
+// quantity `read_vlq` is able to decode back
+static VLQ_MAX: u32 = 0x0FFFFFFF;
+
+// Encode a variable length quantity, 7 bits per byte most significant
+// group first, with the continuation bit set on every byte but the last
+fn write_vlq(writer: &mut Writer, value: u32) -> Result<(), SMFError> {
+    if value > VLQ_MAX {
+        return Err(SMFError::InvalidSMFFile("Value too large for a 4-byte variable length quantity"));
+    }
+    let mut groups = vec![(value & 0x7F) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        groups.push(((rest & 0x7F) as u8) | 0x80);
+        rest >>= 7;
+    }
+    groups.reverse();
+    try!(writer.write(&groups[]));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::MemReader;
+    use super::{read_vlq,write_vlq,read_channel_event,MTrk,VLQ_MAX};
+
+    #[test]
+    fn vlq_round_trips_small_values() {
+        for &value in [0u32, 1, 127, 128, 16383, 16384, 2097151, 2097152].iter() {
+            let mut buf: Vec<u8> = Vec::new();
+            write_vlq(&mut buf, value).ok().unwrap();
+            let mut reader = MemReader::new(buf);
+            assert_eq!(read_vlq(&mut reader).ok().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn vlq_round_trips_max_value() {
+        let mut buf: Vec<u8> = Vec::new();
+        write_vlq(&mut buf, VLQ_MAX).ok().unwrap();
+        let mut reader = MemReader::new(buf);
+        assert_eq!(read_vlq(&mut reader).ok().unwrap(), VLQ_MAX);
+    }
+
+    #[test]
+    fn vlq_rejects_values_too_large_to_round_trip() {
+        let mut buf: Vec<u8> = Vec::new();
+        assert!(write_vlq(&mut buf, VLQ_MAX + 1).is_err());
+    }
+
+    // data_bytes(0xF9) is -3 (reserved, no defined status); casting that
+    // to usize would turn the `while` loop into an unbounded read
+    #[test]
+    fn read_channel_event_rejects_invalid_status_immediately() {
+        let mut reader = MemReader::new(vec![]);
+        assert!(read_channel_event(&mut reader, 0xF9, None).is_err());
+    }
+
+    #[test]
+    fn mtrk_rejects_meta_length_past_end_of_track() {
+        let mut bytes = Vec::new();
+        bytes.push_all(b"MTrk");
+        bytes.push_all(&[0, 0, 0, 4]); // chunk length: 4 bytes follow
+        bytes.push(0x00);              // delta time
+        bytes.push(0xFF);              // meta event
+        bytes.push(0x03);              // TrackName
+        bytes.push(0x7F);              // claims 127 bytes of name data, far past the chunk
+        let mut reader = MemReader::new(bytes);
+        assert!(MTrk::read(&mut reader).is_err());
+    }
+}